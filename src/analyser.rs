@@ -3,7 +3,7 @@ use std::collections::{HashMap, HashSet};
 use full_moon::{
     ast::{
         Assignment, Ast, Block, Expression, FunctionArgs, FunctionCall, FunctionDeclaration,
-        LocalAssignment, LocalFunction, Parameter, Prefix, Var,
+        Index, LocalAssignment, LocalFunction, Parameter, Prefix, Suffix, Var,
     },
     node::Node,
     tokenizer::{Position, Token, TokenReference},
@@ -15,6 +15,11 @@ use tracing::warn;
 pub struct LuaAnalysis {
     global_vars: Vec<VariableDefinition>,
     global_usages: HashMap<String, Vec<Position>>,
+    scopes: Vec<Scope>,
+    // Keyed by `Position::bytes` rather than `Position` itself, since `Position` doesn't
+    // implement `Hash`.
+    resolutions: HashMap<usize, Resolution>,
+    member_paths: HashMap<String, Vec<Vec<String>>>,
 }
 
 impl LuaAnalysis {
@@ -24,7 +29,114 @@ impl LuaAnalysis {
         Self {
             global_vars: visitor.global_vars,
             global_usages: visitor.global_usages,
+            scopes: visitor.scopes,
+            resolutions: visitor.resolutions,
+            member_paths: visitor.member_paths,
+        }
+    }
+
+    /// Returns the member paths accessed off of global `name`, e.g. `["format"]` for
+    /// `string.format(...)`.
+    pub fn member_paths(&self, name: &str) -> &[Vec<String>] {
+        self.member_paths.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Flags locals that are never read and globals that are assigned but never used.
+    ///
+    /// Function parameters and the implicit `self` binding are exempt from the unused-local
+    /// check: an unused parameter is idiomatic Lua (callback signatures, interface methods
+    /// that ignore some arguments), not a mistake worth flagging the way an unused `local` is.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for scope in &self.scopes {
+            for (name, bindings) in &scope.local_vars {
+                for binding in bindings {
+                    if !binding.diagnosable {
+                        continue;
+                    }
+                    let is_read = self.resolutions.values().any(|resolution| {
+                        matches!(
+                            resolution,
+                            Resolution::Local { decl_position, .. }
+                                if *decl_position == binding.decl_position
+                        )
+                    });
+                    if !is_read {
+                        diagnostics.push(Diagnostic {
+                            name: name.clone(),
+                            position: binding.decl_position,
+                            kind: DiagnosticKind::UnusedLocal,
+                        });
+                    }
+                }
+            }
+        }
+
+        for def in &self.global_vars {
+            if let Some(&position) = def.assign_positions.first() {
+                if !self.global_usages.contains_key(&def.name) {
+                    diagnostics.push(Diagnostic {
+                        name: def.name.clone(),
+                        position,
+                        kind: DiagnosticKind::WriteOnlyGlobal,
+                    });
+                }
+            }
         }
+
+        diagnostics
+    }
+
+    /// Returns what the usage at `pos` resolves to, if that position was recorded as one.
+    pub fn resolution_at(&self, pos: Position) -> Option<&Resolution> {
+        self.resolutions.get(&pos.bytes())
+    }
+
+    /// Returns the locals of every scope enclosing `pos`, innermost scope first, followed by
+    /// every assigned global. A name shadowed by an inner local is only returned once, for its
+    /// innermost binding. Locals are included whether they are declared before or after `pos`
+    /// within their scope, and globals that are only used (never assigned) are not included.
+    pub fn visible_names_at(&self, pos: Position) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut names = Vec::new();
+
+        if let Some(mut scope_index) = self.innermost_scope_at(pos) {
+            loop {
+                let scope = &self.scopes[scope_index];
+                for name in scope.local_vars.keys() {
+                    if seen.insert(name.as_str()) {
+                        names.push(name.as_str());
+                    }
+                }
+                match scope.parent {
+                    Some(parent_index) => scope_index = parent_index,
+                    None => break,
+                }
+            }
+        }
+
+        for def in &self.global_vars {
+            if seen.insert(def.name.as_str()) {
+                names.push(def.name.as_str());
+            }
+        }
+
+        names
+    }
+
+    // Picks the innermost scope whose range contains `pos`, i.e. the one with
+    // the latest start position among those that contain it.
+    fn innermost_scope_at(&self, pos: Position) -> Option<usize> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| match (scope.start, scope.end) {
+                (Some(start), Some(end)) => start <= pos && pos <= end,
+                _ => false,
+            })
+            .max_by_key(|(_, scope)| scope.start)
+            .map(|(index, _)| index)
     }
 }
 
@@ -34,10 +146,48 @@ pub struct VariableDefinition {
     assign_positions: Vec<Position>,
 }
 
+/// A warning surfaced by [`LuaAnalysis::diagnostics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub name: String,
+    pub position: Position,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A local binding that is never read within its scope or any child scope.
+    UnusedLocal,
+    /// A global that is assigned but never used anywhere, before or after the assignment.
+    WriteOnlyGlobal,
+}
+
+/// What a name usage refers to, as determined by the resolver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Resolves to a local binding `depth` scopes up from the usage, declared at `decl_position`.
+    Local { decl_position: Position, depth: usize },
+    /// Resolves to a global, since no enclosing scope declares it as a local.
+    Global { name: String },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LocalBinding {
+    decl_position: Position,
+    // Whether an unused binding should be flagged by `LuaAnalysis::diagnostics`. `false` for
+    // function parameters and the implicit `self`, which are unused by convention in Lua.
+    diagnosable: bool,
+}
+
 #[derive(Debug)]
 struct Scope {
-    local_vars: HashSet<String>,
+    // Every binding of a name within this scope, in declaration order, not just the latest:
+    // `local x = 1; local x = 2` redeclares `x` rather than overwriting it, and the first `x`
+    // should still be diagnosable if it's never read before being shadowed.
+    local_vars: HashMap<String, Vec<LocalBinding>>,
     parent: Option<usize>,
+    start: Option<Position>,
+    end: Option<Position>,
 }
 
 #[derive(Debug)]
@@ -46,6 +196,13 @@ struct LuaAnalyserVisitor {
     global_usages: HashMap<String, Vec<Position>>,
     scopes: Vec<Scope>,
     current_scope: Option<usize>,
+    resolutions: HashMap<usize, Resolution>,
+    member_paths: HashMap<String, Vec<Vec<String>>>,
+    // Parameters (and the implicit `self`) of a function whose body hasn't been entered yet.
+    // `visit_function_declaration`/`visit_local_function` fire before the body's own `Block` is
+    // visited, so they can't add these directly to the function's scope - it doesn't exist yet.
+    // `enter_scope` drains this into the next scope it creates, which is always that body.
+    pending_locals: Vec<(String, Position, bool)>,
 }
 
 impl LuaAnalyserVisitor {
@@ -55,6 +212,9 @@ impl LuaAnalyserVisitor {
             global_usages: HashMap::new(),
             scopes: Vec::new(),
             current_scope: None,
+            resolutions: HashMap::new(),
+            member_paths: HashMap::new(),
+            pending_locals: Vec::new(),
         }
     }
 
@@ -83,32 +243,83 @@ impl LuaAnalyserVisitor {
         });
     }
 
-    fn add_local_var(&mut self, name: String) {
+    fn add_local_var(&mut self, name: String, decl_position: Position, diagnosable: bool) {
         let scope_index = self.current_scope.expect("current scope isn't set");
         let scope = self
             .scopes
             .get_mut(scope_index)
             .expect("current scope doesn't exist");
-        scope.local_vars.insert(name);
+        scope.local_vars.entry(name).or_default().push(LocalBinding {
+            decl_position,
+            diagnosable,
+        });
     }
 
-    fn add_global_usage(&mut self, name: String, position: Position) {
-        // Ignore local variables
-        if self.is_local(&name) {
-            return;
+    fn add_global_usage(&mut self, name: String, position: Position) -> Resolution {
+        let resolution = self.resolve(&name);
+        if matches!(resolution, Resolution::Global { .. }) {
+            self.global_usages
+                .entry(name)
+                .and_modify(|usages| usages.push(position))
+                .or_insert(vec![position]);
         }
-        self.global_usages
-            .entry(name)
-            .and_modify(|usages| usages.push(position))
-            .or_insert(vec![position]);
+        self.resolutions.insert(position.bytes(), resolution.clone());
+        resolution
     }
 
-    fn enter_scope(&mut self) {
+    // Records a usage of `root` and, if the index chain reaches past it, the member path
+    // accessed off of it (e.g. root `string`, path `["format"]` for `string.format`). Member
+    // paths are only tracked for globals/modules - a table-valued local like `cfg.timeout` isn't
+    // a module member access and shouldn't show up alongside real ones like `string.format`.
+    fn add_member_usage(&mut self, root: String, path: Vec<String>, position: Position) {
+        let resolution = self.add_global_usage(root.clone(), position);
+        if !path.is_empty() && matches!(resolution, Resolution::Global { .. }) {
+            self.member_paths
+                .entry(root)
+                .and_modify(|paths| paths.push(path.clone()))
+                .or_insert_with(|| vec![path]);
+        }
+    }
+
+    // Walks the scope stack from innermost to outermost, counting hops until a scope
+    // declares `name` as a local. Falls back to `Resolution::Global` if none do.
+    fn resolve(&mut self, name: &str) -> Resolution {
+        let mut scope_index = self.current_scope.expect("current scope isn't set");
+        let mut depth = 0;
+        loop {
+            let scope = self.scopes.get(scope_index).expect("scope doesn't exist");
+            // The most recently declared binding shadows any earlier one with the same name.
+            if let Some(binding) = scope.local_vars.get(name).and_then(|bindings| bindings.last()) {
+                return Resolution::Local {
+                    decl_position: binding.decl_position,
+                    depth,
+                };
+            }
+            match scope.parent {
+                Some(parent_scope) => {
+                    scope_index = parent_scope;
+                    depth += 1;
+                }
+                None => break,
+            }
+        }
+        Resolution::Global {
+            name: name.to_owned(),
+        }
+    }
+
+    fn enter_scope(&mut self, node: &Block) {
         self.scopes.push(Scope {
-            local_vars: HashSet::new(),
+            local_vars: HashMap::new(),
             parent: self.current_scope,
+            start: node.start_position(),
+            end: node.end_position(),
         });
         self.current_scope = Some(self.scopes.len() - 1);
+
+        for (name, decl_position, diagnosable) in std::mem::take(&mut self.pending_locals) {
+            self.add_local_var(name, decl_position, diagnosable);
+        }
     }
 
     fn exit_scope(&mut self) {
@@ -134,7 +345,7 @@ impl LuaAnalyserVisitor {
         let mut scope_index = self.current_scope.expect("current scope isn't set");
         loop {
             let scope = self.scopes.get(scope_index).expect("scope doesn't exist");
-            if scope.local_vars.contains(name) {
+            if scope.local_vars.contains_key(name) {
                 return true;
             }
             let Some(parent_scope) = scope.parent else {
@@ -146,28 +357,64 @@ impl LuaAnalyserVisitor {
     }
 }
 
+// Walks an index/call chain down to its root `Prefix::Name`, collecting the dotted member
+// path up to the first bracketed index or call suffix (e.g. `t.a.b` -> root `t`, path
+// `["a", "b"]`; `string.format(...)` -> root `string`, path `["format"]`).
+fn member_path<'a>(
+    prefix: &Prefix,
+    suffixes: impl Iterator<Item = &'a Suffix>,
+) -> Option<(String, Vec<String>)> {
+    let Prefix::Name(name_token) = prefix else {
+        return None;
+    };
+    let root = name_token.token().to_string().trim().to_owned();
+    let mut path = Vec::new();
+    for suffix in suffixes {
+        let Suffix::Index(Index::Dot { name, .. }) = suffix else {
+            break;
+        };
+        path.push(name.token().to_string().trim().to_owned());
+    }
+    Some((root, path))
+}
+
 impl Visitor for LuaAnalyserVisitor {
     fn visit_assignment(&mut self, assignment: &Assignment) {
         for var in assignment.variables() {
-            let Var::Name(name_token) = var else {
-                // Skip expression assignment for now e.g. `x.y = 123` and `x.y.z() = 321`
-                continue;
-            };
-            let name = name_token.token().to_string().trim().to_owned();
-            if !self.is_local(&name) {
-                self.add_global_var(name, var.start_position());
+            match var {
+                Var::Name(name_token) => {
+                    let name = name_token.token().to_string().trim().to_owned();
+                    if !self.is_local(&name) {
+                        self.add_global_var(name, var.start_position());
+                    }
+                }
+                Var::Expression(var_expr) => {
+                    // e.g. `z.b.a = 3` is a member-write against global `z`, not a definition.
+                    if let Some((root, path)) =
+                        member_path(var_expr.prefix(), var_expr.suffixes())
+                    {
+                        if let Some(position) = var_expr.prefix().start_position() {
+                            self.add_member_usage(root, path, position);
+                        }
+                    }
+                }
+                _ => {}
             }
         }
     }
 
     fn visit_local_assignment(&mut self, local_assign: &LocalAssignment) {
         for name in local_assign.names() {
-            self.add_local_var(name.to_string().trim().to_owned());
+            self.add_local_var(
+                name.to_string().trim().to_owned(),
+                name.token().start_position(),
+                true,
+            );
         }
     }
 
-    fn visit_block(&mut self, _node: &Block) {
-        self.enter_scope();
+    fn visit_block(&mut self, node: &Block) {
+        self.enter_scope(node);
     }
 
     fn visit_block_end(&mut self, _node: &Block) {
@@ -175,18 +422,42 @@ impl Visitor for LuaAnalyserVisitor {
     }
 
     fn visit_function_declaration(&mut self, func_dec: &FunctionDeclaration) {
+        // Methods declared with colon syntax (`function obj:greet()`) receive an implicit
+        // `self` local that never appears as an explicit parameter. It's not diagnosable,
+        // same as any other parameter: real tooling never warns on an unused `self`.
+        if let Some(method_name) = func_dec.name().method_name() {
+            self.pending_locals.push((
+                "self".to_owned(),
+                method_name.token().start_position(),
+                false,
+            ));
+        }
         for param in func_dec.body().parameters() {
             if let Parameter::Name(name) = param {
-                self.add_local_var(name.token().to_string().trim().to_owned());
+                self.pending_locals.push((
+                    name.token().to_string().trim().to_owned(),
+                    name.token().start_position(),
+                    false,
+                ));
             }
         }
     }
 
     fn visit_local_function(&mut self, local_func: &LocalFunction) {
-        self.add_local_var(local_func.name().token().to_string().trim().to_owned());
+        // The function's own name stays in the enclosing scope (not deferred): it must be
+        // visible to the body for recursive calls, and to code after the declaration.
+        self.add_local_var(
+            local_func.name().token().to_string().trim().to_owned(),
+            local_func.name().token().start_position(),
+            true,
+        );
         for param in local_func.body().parameters() {
             if let Parameter::Name(name) = param {
-                self.add_local_var(name.token().to_string().trim().to_owned());
+                self.pending_locals.push((
+                    name.token().to_string().trim().to_owned(),
+                    name.token().start_position(),
+                    false,
+                ));
             }
         }
     }
@@ -201,19 +472,20 @@ impl Visitor for LuaAnalyserVisitor {
                         token.start_position(),
                     );
                 }
-                Var::Expression(_var_expr) => {
-                    todo!();
+                Var::Expression(var_expr) => {
+                    if let Some((root, path)) =
+                        member_path(var_expr.prefix(), var_expr.suffixes())
+                    {
+                        if let Some(position) = var_expr.prefix().start_position() {
+                            self.add_member_usage(root, path, position);
+                        }
+                    }
                 }
                 _ => {}
             },
-            Expression::FunctionCall(func_call) => {
-                if let Some(position) = func_call.start_position() {
-                    self.add_global_usage(
-                        func_call.prefix().to_string().trim().to_owned(),
-                        position,
-                    );
-                }
-            }
+            // `visit_function_call` already fires for the nested `FunctionCall`, however it's
+            // reached (statement or sub-expression) - recording it here too would double-count.
+            Expression::FunctionCall(_) => {}
             _ => {}
         }
     }
@@ -223,7 +495,9 @@ impl Visitor for LuaAnalyserVisitor {
             // If there is no position we can't provide useful data so ignore it
             return;
         };
-        self.add_global_usage(func_call.prefix().to_string().trim().to_owned(), position);
+        let (root, path) = member_path(func_call.prefix(), func_call.suffixes())
+            .unwrap_or_else(|| (func_call.prefix().to_string().trim().to_owned(), Vec::new()));
+        self.add_member_usage(root, path, position);
     }
 }
 
@@ -231,7 +505,7 @@ impl Visitor for LuaAnalyserVisitor {
 mod tests {
     use crate::analyser::LuaAnalyserVisitor;
 
-    use super::LuaAnalysis;
+    use super::{DiagnosticKind, LuaAnalysis, Resolution};
     use full_moon::{parse, visitors::Visitor};
 
     #[test]
@@ -340,4 +614,225 @@ mod tests {
         assert_eq!(analysis.global_usages.keys().len(), 1);
         assert_eq!(analysis.global_usages.get("print").unwrap().len(), 1);
     }
+
+    #[test]
+    fn visible_names_at_dedups_shadowed_locals() {
+        let code = r#"
+        local x = 1
+        function example()
+            local x = 2
+        end
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+
+        let inner_scope = analysis
+            .scopes
+            .iter()
+            .find(|scope| scope.parent.is_some())
+            .expect("function body should have its own scope");
+        let pos = inner_scope
+            .start
+            .expect("non-empty block should have a start position");
+
+        let names = analysis.visible_names_at(pos);
+        assert_eq!(names.iter().filter(|&&name| name == "x").count(), 1);
+    }
+
+    #[test]
+    fn resolves_local_and_global_usages() {
+        let code = r#"
+        local x = 1
+        function example()
+            print(x)
+        end
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+
+        let local_resolutions: Vec<_> = analysis
+            .resolutions
+            .values()
+            .filter(|resolution| matches!(resolution, Resolution::Local { .. }))
+            .collect();
+        assert_eq!(local_resolutions.len(), 1);
+        let Resolution::Local { depth, .. } = local_resolutions[0] else {
+            unreachable!();
+        };
+        assert_eq!(*depth, 1);
+
+        let global_resolutions = analysis
+            .resolutions
+            .values()
+            .filter(|resolution| matches!(resolution, Resolution::Global { name } if name == "print"))
+            .count();
+        assert_eq!(global_resolutions, 1);
+    }
+
+    #[test]
+    fn resolution_at_looks_up_by_position() {
+        let code = r#"
+        print(1)
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+
+        let pos = *analysis
+            .global_usages
+            .get("print")
+            .and_then(|usages| usages.first())
+            .expect("print should have been recorded as a global usage");
+
+        assert!(matches!(
+            analysis.resolution_at(pos),
+            Some(Resolution::Global { name }) if name == "print"
+        ));
+    }
+
+    #[test]
+    fn member_paths_are_recorded() {
+        let code = r#"string.format("%d", 1)"#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert_eq!(analysis.member_paths("string"), &[vec!["format".to_owned()]]);
+    }
+
+    #[test]
+    fn member_paths_ignore_local_table_fields() {
+        let code = r#"
+        local cfg = {}
+        cfg.timeout = 5
+        print(cfg.timeout)
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert!(analysis.member_paths("cfg").is_empty());
+    }
+
+    #[test]
+    fn nested_function_call_usage_is_recorded_once() {
+        let code = r#"local a = string.format("%d", 1)"#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert_eq!(analysis.global_usages.get("string").unwrap().len(), 1);
+        assert_eq!(analysis.member_paths("string"), &[vec!["format".to_owned()]]);
+    }
+
+    #[test]
+    fn diagnostics_flags_unused_locals_and_write_only_globals() {
+        let code = r#"
+        local unused = 1
+        local used = 2
+        print(used)
+        written = 3
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        let diagnostics = analysis.diagnostics();
+
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.name == "unused" && d.kind == DiagnosticKind::UnusedLocal));
+        assert!(!diagnostics.iter().any(|d| d.name == "used"));
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.name == "written" && d.kind == DiagnosticKind::WriteOnlyGlobal));
+    }
+
+    #[test]
+    fn redeclared_locals_are_diagnosed_independently() {
+        let code = r#"
+        local x = 1
+        local x = 2
+        print(x)
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        let diagnostics = analysis.diagnostics();
+
+        assert_eq!(
+            diagnostics
+                .iter()
+                .filter(|d| d.name == "x" && d.kind == DiagnosticKind::UnusedLocal)
+                .count(),
+            1,
+            "the first, shadowed `x` should be flagged as unused even though the second is read"
+        );
+    }
+
+    #[test]
+    fn diagnostics_ignores_unused_function_parameters() {
+        let code = r#"
+        function example(unused_param)
+        end
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert!(analysis.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn colon_methods_bind_implicit_self() {
+        let code = r#"
+        function obj:greet(name)
+            self.name = name
+        end
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert!(!analysis.global_usages.contains_key("self"));
+    }
+
+    #[test]
+    fn function_parameters_are_not_visible_outside_the_body() {
+        let code = r#"
+        function example(secret_param)
+        end
+        q = 1
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+
+        let pos = analysis
+            .global_vars
+            .iter()
+            .find(|def| def.name == "q")
+            .and_then(|def| def.assign_positions.first())
+            .copied()
+            .expect("q should have been recorded as a global assignment");
+
+        assert!(!analysis.visible_names_at(pos).contains(&"secret_param"));
+    }
+
+    #[test]
+    fn implicit_self_is_not_visible_outside_the_method_body() {
+        let code = r#"
+        function obj:greet()
+        end
+        q = 1
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+
+        let pos = analysis
+            .global_vars
+            .iter()
+            .find(|def| def.name == "q")
+            .and_then(|def| def.assign_positions.first())
+            .copied()
+            .expect("q should have been recorded as a global assignment");
+
+        assert!(!analysis.visible_names_at(pos).contains(&"self"));
+    }
+
+    #[test]
+    fn unused_implicit_self_is_not_a_diagnostic() {
+        let code = r#"
+        function obj:greet()
+        end
+        "#;
+        let ast = parse(code).unwrap();
+        let analysis = LuaAnalysis::from_ast(&ast);
+        assert!(analysis.diagnostics().iter().all(|d| d.name != "self"));
+    }
 }